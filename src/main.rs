@@ -4,7 +4,7 @@ use metal::*;
 
 use winit::platform::macos::WindowExtMacOS;
 use winit::{
-    event::{Event, WindowEvent},
+    event::{ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
 };
 
@@ -14,8 +14,16 @@ use cocoa::{appkit::NSView, base::id as cocoa_id};
 use core_graphics::geometry::CGSize;
 use objc::runtime::YES;
 
+use dispatch::Semaphore;
+
 use std::mem;
 
+// How many frames' worth of per-frame uniform buffers to keep in flight at
+// once. Triple buffering lets the CPU write frame N+1's uniforms while the
+// GPU is still reading frame N-1's, without the CPU ever writing into a
+// buffer the GPU hasn't finished reading.
+const MAX_INFLIGHT_FRAMES: usize = 3;
+
 // Declare the data structures needed to carry vertex layout to
 // metal shading language(MSL) program. Use #[repr(C)], to make
 // the data structure compatible with C++ type data structure
@@ -34,6 +42,158 @@ pub struct AAPLVertex {
     t: texture_coordinate,
 }
 
+// Column-major 4x4 matrix passed to vertexShader so it can apply pan/zoom
+// to incoming clip-space positions.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TransformUniforms {
+    matrix: [f32; 16],
+}
+
+// A clip rectangle in logical points — the same unit as `LogicalSize` /
+// `WindowBuilder::with_inner_size`, NOT the physical pixels winit's raw
+// `CursorMoved`/`inner_size()` payloads carry. Callers building a `ClipRect`
+// from those must convert first, e.g. via `.to_logical(window.scale_factor())`.
+#[derive(Debug, Clone, Copy)]
+pub struct ClipRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+// Project a logical clip rectangle into drawable pixels, accounting for the
+// retina backing-scale factor, and clamp it to the drawable bounds so
+// `set_scissor_rect` never receives an out-of-range rectangle. Returns `None`
+// if the rect is fully outside the drawable or would clamp to zero width or
+// height, since Metal's validation layer rejects a degenerate scissor rect.
+fn scissor_rect_for(clip: ClipRect, scale_factor: f64, drawable_size: (f64, f64)) -> Option<MTLScissorRect> {
+    let max_width = drawable_size.0 as u64;
+    let max_height = drawable_size.1 as u64;
+
+    // Clamp both edges on each axis, so a rect that starts left of or above
+    // the drawable loses the part that lies outside it.
+    let left = ((clip.x * scale_factor).round().max(0.0) as u64).min(max_width);
+    let top = ((clip.y * scale_factor).round().max(0.0) as u64).min(max_height);
+    let right = (((clip.x + clip.width) * scale_factor).round().max(0.0) as u64).min(max_width);
+    let bottom = (((clip.y + clip.height) * scale_factor).round().max(0.0) as u64).min(max_height);
+
+    if right <= left || bottom <= top {
+        return None;
+    }
+
+    Some(MTLScissorRect {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+// Pan offset (in clip space) and zoom scale for interactively inspecting
+// the sampled texture. Composed as scale-then-translate.
+#[derive(Debug, Clone, Copy)]
+struct ViewTransform {
+    pan: (f32, f32),
+    zoom: f32,
+}
+
+impl ViewTransform {
+    fn identity() -> Self {
+        ViewTransform {
+            pan: (0.0, 0.0),
+            zoom: 1.0,
+        }
+    }
+
+    fn to_uniforms(&self) -> TransformUniforms {
+        let s = self.zoom;
+        let (tx, ty) = self.pan;
+        #[rustfmt::skip]
+        let matrix = [
+            s,    0.0,  0.0, 0.0,
+            0.0,  s,    0.0, 0.0,
+            0.0,  0.0,  1.0, 0.0,
+            tx,   ty,   0.0, 1.0,
+        ];
+        TransformUniforms { matrix }
+    }
+}
+
+// Metal's texture origin is top-left with V increasing downward, but decoded
+// image rows aren't guaranteed to agree on that, so the quad's texture
+// coordinates need to be built for whichever convention the source uses.
+//
+// `Downward` is the correct default for `prepare_texture_from_file`/the
+// `image` crate as used here: `image::open(...).into_rgba()` always decodes
+// into row-major order with row 0 at the top of the image regardless of the
+// source format's own storage order, and `replace_region` writes that row 0
+// into texture row 0 (Metal's origin), so the two already agree — `Upward`
+// exists for sources that bypass `image` and hand in bottom-up row data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VDirection {
+    // V increases downward, matching a standard top-left-origin image.
+    Downward,
+    // V increases upward; flips `v -> 1.0 - v` relative to `Downward`.
+    Upward,
+}
+
+// A vertex buffer together with the index buffer that draws it. Letting
+// callers feed a deduplicated vertex array plus indices avoids duplicating
+// shared corners, which is the standard path for sprite/UI batching where a
+// mesh can easily exceed 64k vertices if every triangle repeats its own copy.
+pub struct Mesh {
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+    pub index_count: u64,
+}
+
+fn prepare_quad_mesh(device: &DeviceRef, direction: VDirection) -> Mesh {
+    let v = |v: f32| match direction {
+        VDirection::Downward => v,
+        VDirection::Upward => 1.0 - v,
+    };
+
+    // Four unique corners; the two triangles of the quad share an edge
+    // instead of duplicating its vertices.
+    let vertex_data = [
+        AAPLVertex {
+            p: position(1.0, -1.0),
+            t: texture_coordinate(1.0, v(1.0)),
+        },
+        AAPLVertex {
+            p: position(1.0, 1.0),
+            t: texture_coordinate(1.0, v(0.0)),
+        },
+        AAPLVertex {
+            p: position(-1.0, -1.0),
+            t: texture_coordinate(0.0, v(1.0)),
+        },
+        AAPLVertex {
+            p: position(-1.0, 1.0),
+            t: texture_coordinate(0.0, v(0.0)),
+        },
+    ];
+    let index_data: [u16; 6] = [0, 1, 2, 2, 1, 3];
+
+    let vertex_buffer = device.new_buffer_with_data(
+        vertex_data.as_ptr() as *const _,
+        (vertex_data.len() * mem::size_of::<AAPLVertex>()) as u64,
+        MTLResourceOptions::CPUCacheModeDefaultCache | MTLResourceOptions::StorageModeManaged,
+    );
+    let index_buffer = device.new_buffer_with_data(
+        index_data.as_ptr() as *const _,
+        (index_data.len() * mem::size_of::<u16>()) as u64,
+        MTLResourceOptions::CPUCacheModeDefaultCache | MTLResourceOptions::StorageModeManaged,
+    );
+
+    Mesh {
+        vertex_buffer,
+        index_buffer,
+        index_count: index_data.len() as u64,
+    }
+}
+
 fn prepare_render_pass_descriptor(descriptor: &RenderPassDescriptorRef, texture: &TextureRef) {
     let color_attachment = descriptor.color_attachments().object_at(0).unwrap();
 
@@ -44,34 +204,103 @@ fn prepare_render_pass_descriptor(descriptor: &RenderPassDescriptorRef, texture:
     color_attachment.set_store_action(MTLStoreAction::Store);
 }
 
-fn prepare_pipeline_state(device: &DeviceRef, library: &Library) -> RenderPipelineState {
+// Whether the pipeline composites the sampled texture's alpha channel over
+// the existing framebuffer contents (straight alpha, premultiplied result)
+// or writes it out opaque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    Opaque,
+    AlphaBlend,
+}
+
+fn prepare_pipeline_state(
+    device: &DeviceRef,
+    library: &Library,
+    blend_mode: BlendMode,
+) -> RenderPipelineState {
     let vert = library.get_function("vertexShader", None).unwrap();
     let frag = library.get_function("samplingShader", None).unwrap();
 
     let pipeline_state_descriptor = RenderPipelineDescriptor::new();
     pipeline_state_descriptor.set_vertex_function(Some(&vert));
     pipeline_state_descriptor.set_fragment_function(Some(&frag));
-    pipeline_state_descriptor
+
+    let color_attachment = pipeline_state_descriptor
         .color_attachments()
         .object_at(0)
-        .unwrap()
-        .set_pixel_format(MTLPixelFormat::BGRA8Unorm);
+        .unwrap();
+    color_attachment.set_pixel_format(MTLPixelFormat::BGRA8Unorm);
+
+    if blend_mode == BlendMode::AlphaBlend {
+        color_attachment.set_blending_enabled(true);
+        color_attachment.set_rgb_blend_operation(MTLBlendOperation::Add);
+        color_attachment.set_alpha_blend_operation(MTLBlendOperation::Add);
+        color_attachment.set_source_rgb_blend_factor(MTLBlendFactor::SourceAlpha);
+        color_attachment.set_destination_rgb_blend_factor(MTLBlendFactor::OneMinusSourceAlpha);
+        color_attachment.set_source_alpha_blend_factor(MTLBlendFactor::One);
+        color_attachment.set_destination_alpha_blend_factor(MTLBlendFactor::OneMinusSourceAlpha);
+    }
 
     device
         .new_render_pipeline_state(&pipeline_state_descriptor)
         .unwrap()
 }
 
-fn prepare_texture_from_file(device: &DeviceRef, source: &str) -> Texture {
+// Compile the MSL shader library at runtime from its `.metal` source so that
+// changes to `vertexShader`/`samplingShader` don't require an offline
+// `metallib` build step. When a precompiled `.metallib` is present (e.g. a
+// release build shipped without the Metal toolchain installed) it is used
+// instead, since loading a precompiled binary is cheaper than recompiling.
+fn prepare_shader_library(
+    device: &DeviceRef,
+    metal_source_path: &str,
+    metallib_path: &str,
+) -> Result<Library, String> {
+    if std::path::Path::new(metallib_path).exists() {
+        return device
+            .new_library_with_file(metallib_path)
+            .map_err(|e| e.to_string());
+    }
+
+    let source = std::fs::read_to_string(metal_source_path)
+        .map_err(|e| format!("failed to read {}: {}", metal_source_path, e))?;
+
+    device
+        .new_library_with_source(&source, &CompileOptions::new())
+        .map_err(|e| e.to_string())
+}
+
+// Build a sampler for the textured quad. Exposing the filter and address
+// mode lets callers pick trilinear filtering for magnified/minified views
+// and choose how out-of-bounds texture coordinates wrap.
+fn prepare_sampler_state(
+    device: &DeviceRef,
+    min_mag_filter: MTLSamplerMinMagFilter,
+    mip_filter: MTLSamplerMipFilter,
+    address_mode: MTLSamplerAddressMode,
+) -> SamplerState {
+    let sd = SamplerDescriptor::new();
+    sd.set_min_filter(min_mag_filter);
+    sd.set_mag_filter(min_mag_filter);
+    sd.set_mip_filter(mip_filter);
+    sd.set_address_mode_s(address_mode);
+    sd.set_address_mode_t(address_mode);
+
+    device.new_sampler(&sd)
+}
+
+fn prepare_texture_from_file(device: &DeviceRef, command_queue: &CommandQueue, source: &str) -> Texture {
     let image = image::open(source);
     let image_buffer = image.unwrap().into_rgba();
     let width: u64 = image_buffer.width().into();
     let height: u64 = image_buffer.height().into();
     println!("Height {} and width are {}", height, width);
+    let mip_level_count = ((width.max(height) as f64).log2().floor() as u64) + 1;
     let td = TextureDescriptor::new();
     td.set_width(width);
     td.set_height(height);
     td.set_pixel_format(MTLPixelFormat::BGRA8Unorm);
+    td.set_mipmap_level_count(mip_level_count);
 
     let texture: Texture = device.new_texture(&td);
     let reg = MTLRegion {
@@ -87,19 +316,27 @@ fn prepare_texture_from_file(device: &DeviceRef, source: &str) -> Texture {
     println!("The image bytes length {}", &l.len());
     texture.replace_region(reg, 0, bytes_per_row, l.as_ptr() as *const std::ffi::c_void);
 
+    // Generate the remaining mip levels from the base level we just uploaded
+    // so the fragment shader can do proper trilinear filtering.
+    let command_buffer = command_queue.new_command_buffer();
+    let blit_encoder = command_buffer.new_blit_command_encoder();
+    blit_encoder.generate_mipmaps(&texture);
+    blit_encoder.end_encoding();
+    command_buffer.commit();
+    command_buffer.wait_until_completed();
+
     return texture;
 }
 
 fn main() {
     // Create a window for viewing the content
     let event_loop = EventLoop::new();
-    let events_loop = winit::event_loop::EventLoop::new();
     let size = winit::dpi::LogicalSize::new(800, 600);
 
     let window = winit::window::WindowBuilder::new()
         .with_inner_size(size)
         .with_title("Sampling Textures".to_string())
-        .build(&events_loop)
+        .build(&event_loop)
         .unwrap();
 
     // Set up the GPU device found in the system
@@ -126,51 +363,54 @@ fn main() {
     let draw_size = window.inner_size();
     layer.set_drawable_size(CGSize::new(draw_size.width as f64, draw_size.height as f64));
 
-    let vbuf = {
-        //let vertex_data = create_vertex_points_for_circle();
-        //let vertex_data = vertex_data.as_slice();
-        let vertex_data = [
-            AAPLVertex {
-                p: position(1.0, -1.0),
-                t: texture_coordinate(1.0, 1.0),
-            },
-            AAPLVertex {
-                p: position(1.0, 1.0),
-                t: texture_coordinate(1.0, 0.0),
-            },
-            AAPLVertex {
-                p: position(-1.0, -1.0),
-                t: texture_coordinate(0.0, 1.0),
-            },
-            AAPLVertex {
-                p: position(-1.0, -1.0),
-                t: texture_coordinate(0.0, 1.0),
-            },
-            AAPLVertex {
-                p: position(1.0, 1.0),
-                t: texture_coordinate(1.0, 0.0),
-            },
-            AAPLVertex {
-                p: position(-1.0, 1.0),
-                t: texture_coordinate(0.0, 0.0),
-            },
-        ];
-
-        device.new_buffer_with_data(
-            vertex_data.as_ptr() as *const _,
-            (vertex_data.len() * mem::size_of::<AAPLVertex>()) as u64,
-            MTLResourceOptions::CPUCacheModeDefaultCache | MTLResourceOptions::StorageModeManaged,
-        )
-    };
+    // Toggled between `Downward` and `Upward` by the 'V' key, so the alternate
+    // branch is actually exercised instead of sitting dead.
+    let mut v_direction = VDirection::Downward;
+    let mut mesh = prepare_quad_mesh(&device, v_direction);
 
-    // Use the metallib file generated out of .metal shader file
-    let library = device.new_library_with_file("shaders.metallib").unwrap();
+    // Load a precompiled shaders.metallib if one is shipped alongside the
+    // binary; otherwise compile shaders.metal at runtime.
+    let library = prepare_shader_library(&device, "shaders.metal", "shaders.metallib")
+        .expect("failed to prepare shader library");
 
-    // The render pipeline generated from the vertex and fragment shaders in the .metal shader file.
-    let pipeline_state = prepare_pipeline_state(&device, &library);
+    // The render pipeline generated from the vertex and fragment shaders in the .metal shader
+    // file. Toggled between opaque and alpha-blended by the 'B' key.
+    let mut blend_mode = BlendMode::AlphaBlend;
+    let mut pipeline_state = prepare_pipeline_state(&device, &library, blend_mode);
 
     // Set the texture here
-    let tref = prepare_texture_from_file(&device, "Image.tga");
+    let tref = prepare_texture_from_file(&device, &command_queue, "Image.tga");
+
+    // Trilinear filtering with edge clamping for the sampled texture.
+    let sampler_state = prepare_sampler_state(
+        &device,
+        MTLSamplerMinMagFilter::Linear,
+        MTLSamplerMipFilter::Linear,
+        MTLSamplerAddressMode::ClampToEdge,
+    );
+
+    // Pan/zoom state for interactively inspecting the texture. One uniform
+    // buffer per in-flight frame, written each frame before it's bound to
+    // the vertex shader, so the CPU never overwrites a buffer the GPU is
+    // still reading from a prior frame.
+    let uniform_buffers: Vec<Buffer> = (0..MAX_INFLIGHT_FRAMES)
+        .map(|_| {
+            device.new_buffer(
+                mem::size_of::<TransformUniforms>() as u64,
+                MTLResourceOptions::CPUCacheModeDefaultCache | MTLResourceOptions::StorageModeManaged,
+            )
+        })
+        .collect();
+    let frame_semaphore = Semaphore::new(MAX_INFLIGHT_FRAMES as isize);
+    let mut frame_index: usize = 0;
+    let mut view_transform = ViewTransform::identity();
+    let mut last_cursor_pos: Option<(f64, f64)> = None;
+    let mut dragging = false;
+
+    // Logical-space panes to render the texture into. Empty means render to
+    // the full drawable. Toggled by the 'C' key, which splits the window into
+    // two side-by-side panes so the scissor path has a visible demo.
+    let mut clip_rects: Vec<ClipRect> = Vec::new();
 
     event_loop.run(move |event, _, control_flow| {
         // ControlFlow::Wait pauses the event loop if no events are available to process.
@@ -186,6 +426,147 @@ fn main() {
                 println!("The close button was pressed; stopping");
                 *control_flow = ControlFlow::Exit
             }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => {
+                // Keep the drawable size (and thus aspect ratio) in sync with the window.
+                layer.set_drawable_size(CGSize::new(size.width as f64, size.height as f64));
+            }
+            Event::WindowEvent {
+                event: WindowEvent::MouseWheel { delta, .. },
+                ..
+            } => {
+                let scroll = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y as f64,
+                    MouseScrollDelta::PixelDelta(pos) => pos.y / 40.0,
+                };
+                let zoom_factor = 1.0 + scroll * 0.1;
+
+                // Zoom about the cursor: keep the point currently under it fixed
+                // in clip space rather than zooming around the quad's center.
+                if let Some((cx, cy)) = last_cursor_pos {
+                    let size = window.inner_size();
+                    let clip_x = (cx / size.width as f64) * 2.0 - 1.0;
+                    let clip_y = 1.0 - (cy / size.height as f64) * 2.0;
+
+                    let old_zoom = view_transform.zoom as f64;
+                    let new_zoom = (old_zoom * zoom_factor).clamp(0.1, 10.0);
+                    let local_x = (clip_x - view_transform.pan.0 as f64) / old_zoom;
+                    let local_y = (clip_y - view_transform.pan.1 as f64) / old_zoom;
+
+                    view_transform.zoom = new_zoom as f32;
+                    view_transform.pan.0 = (clip_x - new_zoom * local_x) as f32;
+                    view_transform.pan.1 = (clip_y - new_zoom * local_y) as f32;
+                }
+                window.request_redraw();
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state,
+                        button: MouseButton::Left,
+                        ..
+                    },
+                ..
+            } => {
+                dragging = state == ElementState::Pressed;
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                ..
+            } => {
+                let pos = (position.x, position.y);
+                if dragging {
+                    if let Some((lx, ly)) = last_cursor_pos {
+                        let size = window.inner_size();
+                        let dx = (pos.0 - lx) / (size.width as f64 / 2.0);
+                        let dy = (pos.1 - ly) / (size.height as f64 / 2.0);
+                        view_transform.pan.0 += dx as f32;
+                        view_transform.pan.1 -= dy as f32;
+                    }
+                    window.request_redraw();
+                }
+                last_cursor_pos = Some(pos);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::B),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Toggle between opaque and alpha-blended rendering of the texture.
+                blend_mode = match blend_mode {
+                    BlendMode::Opaque => BlendMode::AlphaBlend,
+                    BlendMode::AlphaBlend => BlendMode::Opaque,
+                };
+                pipeline_state = prepare_pipeline_state(&device, &library, blend_mode);
+                window.request_redraw();
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::V),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Toggle the quad's texture-coordinate V direction.
+                v_direction = match v_direction {
+                    VDirection::Downward => VDirection::Upward,
+                    VDirection::Upward => VDirection::Downward,
+                };
+                mesh = prepare_quad_mesh(&device, v_direction);
+                window.request_redraw();
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::C),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Toggle a two-pane scissor demo: split the window into
+                // left/right halves, each drawn with its own clip rectangle.
+                clip_rects = if clip_rects.is_empty() {
+                    let size = window.inner_size().to_logical::<f64>(window.scale_factor());
+                    vec![
+                        ClipRect {
+                            x: 0.0,
+                            y: 0.0,
+                            width: size.width / 2.0,
+                            height: size.height,
+                        },
+                        ClipRect {
+                            x: size.width / 2.0,
+                            y: 0.0,
+                            width: size.width / 2.0,
+                            height: size.height,
+                        },
+                    ]
+                } else {
+                    Vec::new()
+                };
+                window.request_redraw();
+            }
             Event::MainEventsCleared => {
                 // Queue a RedrawRequested event.
                 window.request_redraw();
@@ -194,33 +575,97 @@ fn main() {
                 // It's preferrable to render in this event rather than in MainEventsCleared, since
                 // rendering in here allows the program to gracefully handle redraws requested
                 // by the OS.
-                let drawable = match layer.next_drawable() {
-                    Some(drawable) => drawable,
-                    None => return,
-                };
-
-                // Obtain a renderPassDescriptor generated from the view's drawable textures.
-                let render_pass_descriptor = RenderPassDescriptor::new();
-                prepare_render_pass_descriptor(&render_pass_descriptor, drawable.texture());
-
-                // Create a new command buffer for each render pass to the current drawable
-                let command_buffer = command_queue.new_command_buffer();
-
-                // Create a render command encoder.
-                let encoder = command_buffer.new_render_command_encoder(&render_pass_descriptor);
-                encoder.set_render_pipeline_state(&pipeline_state);
-                // Pass in the parameter data.
-                encoder.set_vertex_buffer(0, Some(&vbuf), 0);
-                encoder.set_fragment_texture(0, Some(&tref));
-                // Draw the triangles which will eventually form the circle.
-                encoder.draw_primitives(MTLPrimitiveType::Triangle, 0, 6);
-                encoder.end_encoding();
-
-                // Schedule a present once the framebuffer is complete using the current drawable.
-                command_buffer.present_drawable(&drawable);
-
-                // Finalize rendering here & push the command buffer to the GPU.
-                command_buffer.commit();
+                //
+                // Drain the autorelease pool at the end of every frame so the transient
+                // Cocoa/Metal objects created below (drawable, descriptors, command
+                // buffer, encoder) don't accumulate for the lifetime of the event loop.
+                objc::rc::autoreleasepool(|| {
+                    // Block here, rather than overwrite a uniform buffer the GPU might
+                    // still be reading from MAX_INFLIGHT_FRAMES ago, if the CPU is
+                    // producing frames faster than the GPU can drain them.
+                    frame_semaphore.wait();
+
+                    let drawable = match layer.next_drawable() {
+                        Some(drawable) => drawable,
+                        None => {
+                            frame_semaphore.signal();
+                            return;
+                        }
+                    };
+
+                    // Obtain a renderPassDescriptor generated from the view's drawable textures.
+                    let render_pass_descriptor = RenderPassDescriptor::new();
+                    prepare_render_pass_descriptor(&render_pass_descriptor, drawable.texture());
+
+                    // Create a new command buffer for each render pass to the current drawable
+                    let command_buffer = command_queue.new_command_buffer();
+
+                    // Refresh the pan/zoom transform for this frame in its own slot of the
+                    // uniform ring buffer.
+                    let uniform_buf = &uniform_buffers[frame_index];
+                    unsafe {
+                        let contents = uniform_buf.contents() as *mut TransformUniforms;
+                        *contents = view_transform.to_uniforms();
+                    }
+                    uniform_buf
+                        .did_modify_range(NSRange::new(0, mem::size_of::<TransformUniforms>() as u64));
+                    frame_index = (frame_index + 1) % MAX_INFLIGHT_FRAMES;
+
+                    // Release this frame's slot back to the pool once the GPU is done
+                    // reading from it.
+                    let completion_semaphore = frame_semaphore.clone();
+                    command_buffer.add_completed_handler(move |_| {
+                        completion_semaphore.signal();
+                    });
+
+                    // Create a render command encoder.
+                    let encoder = command_buffer.new_render_command_encoder(&render_pass_descriptor);
+                    encoder.set_render_pipeline_state(&pipeline_state);
+                    // Pass in the parameter data.
+                    encoder.set_vertex_buffer(0, Some(&mesh.vertex_buffer), 0);
+                    encoder.set_vertex_buffer(1, Some(uniform_buf), 0);
+                    encoder.set_fragment_texture(0, Some(&tref));
+                    encoder.set_fragment_sampler_state(0, Some(&sampler_state));
+
+                    // Draw the quad's two triangles from the deduplicated vertex array,
+                    // once per clip rectangle (or once, unclipped, if there are none).
+                    let draw = |encoder: &RenderCommandEncoderRef| {
+                        encoder.draw_indexed_primitives(
+                            MTLPrimitiveType::Triangle,
+                            mesh.index_count,
+                            MTLIndexType::UInt16,
+                            &mesh.index_buffer,
+                            0,
+                        );
+                    };
+                    if clip_rects.is_empty() {
+                        draw(&encoder);
+                    } else {
+                        let scale_factor = window.scale_factor();
+                        let drawable_size = layer.drawable_size();
+                        for clip in &clip_rects {
+                            let scissor_rect = scissor_rect_for(
+                                *clip,
+                                scale_factor,
+                                (drawable_size.width, drawable_size.height),
+                            );
+                            let scissor_rect = match scissor_rect {
+                                Some(scissor_rect) => scissor_rect,
+                                // Outside the drawable or clamped to zero size; nothing to draw.
+                                None => continue,
+                            };
+                            encoder.set_scissor_rect(scissor_rect);
+                            draw(&encoder);
+                        }
+                    }
+                    encoder.end_encoding();
+
+                    // Schedule a present once the framebuffer is complete using the current drawable.
+                    command_buffer.present_drawable(&drawable);
+
+                    // Finalize rendering here & push the command buffer to the GPU.
+                    command_buffer.commit();
+                });
             }
             _ => (),
         }